@@ -11,13 +11,88 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{header::HeaderMap, status::StatusCode, Command, Error, Message, Subscriber};
+use super::{
+    header::HeaderMap, status::StatusCode, Command, ConnectOptions, Error, Message, Subscriber,
+};
 use bytes::Bytes;
 use futures::stream::StreamExt;
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::io::{self, ErrorKind};
-use tokio::sync::mpsc;
+use std::time::Duration;
+use tokio::io;
+use tokio::sync::{mpsc, oneshot, Mutex};
+
+/// Holds the single long-lived subscription used to multiplex replies to all
+/// outstanding requests, keyed by the unique token appended to the shared
+/// inbox prefix (`<prefix><token>`).
+#[derive(Debug)]
+struct Multiplexer {
+    prefix: String,
+    senders: Mutex<HashMap<String, oneshot::Sender<Message>>>,
+}
+
+/// Deregisters a pending request's entry from [`Multiplexer::senders`] if the
+/// request is abandoned (e.g. cancelled by `tokio::time::timeout`) before the
+/// router in [`Client::mux_subscribe`] has a chance to remove it itself. Call
+/// [`MuxGuard::disarm`] once the entry is known to be gone so the common,
+/// non-cancelled path doesn't pay for an extra task spawn.
+struct MuxGuard {
+    mux: Arc<Multiplexer>,
+    token: String,
+    armed: bool,
+}
+
+impl MuxGuard {
+    fn new(mux: Arc<Multiplexer>, token: String) -> MuxGuard {
+        MuxGuard {
+            mux,
+            token,
+            armed: true,
+        }
+    }
+
+    fn disarm(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for MuxGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let mux = self.mux.clone();
+        let token = std::mem::take(&mut self.token);
+        tokio::spawn(async move {
+            mux.senders.lock().await.remove(&token);
+        });
+    }
+}
+
+/// Error returned by [`Client::request`], [`Client::request_with_headers`] and
+/// [`Client::request_timeout`] when a reply could not be obtained.
+#[derive(Debug)]
+pub enum RequestError {
+    /// The server reported that nothing is listening on the request subject.
+    NoResponders,
+    /// No reply arrived within the request's timeout.
+    Timeout,
+    /// The connection was closed before a reply arrived.
+    BrokenPipe,
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::NoResponders => write!(f, "nats: no responders"),
+            RequestError::Timeout => write!(f, "nats: request timed out"),
+            RequestError::BrokenPipe => write!(f, "nats: did not receive any message"),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
 
 /// Client is a `Clonable` handle to NATS connection.
 /// Client should not be created directly. Instead, one of two methods can be used:
@@ -27,14 +102,28 @@ pub struct Client {
     sender: mpsc::Sender<Command>,
     next_subscription_id: Arc<AtomicU64>,
     subscription_capacity: usize,
+    no_multiplexing: bool,
+    mux: Arc<Mutex<Option<Arc<Multiplexer>>>>,
+    request_timeout: Option<Duration>,
 }
 
 impl Client {
     pub(crate) fn new(sender: mpsc::Sender<Command>, capacity: usize) -> Client {
+        Client::new_with_options(sender, capacity, &ConnectOptions::default())
+    }
+
+    pub(crate) fn new_with_options(
+        sender: mpsc::Sender<Command>,
+        capacity: usize,
+        options: &ConnectOptions,
+    ) -> Client {
         Client {
             sender,
             next_subscription_id: Arc::new(AtomicU64::new(0)),
             subscription_capacity: capacity,
+            no_multiplexing: options.no_multiplexing,
+            mux: Arc::new(Mutex::new(None)),
+            request_timeout: options.request_timeout,
         }
     }
 
@@ -103,53 +192,178 @@ impl Client {
     }
 
     pub async fn request(&self, subject: String, payload: Bytes) -> Result<Message, Error> {
+        self.do_request(subject, None, payload, self.request_timeout)
+            .await
+            .map_err(|err| Box::new(err) as Error)
+    }
+
+    pub async fn request_with_headers(
+        &self,
+        subject: String,
+        headers: HeaderMap,
+        payload: Bytes,
+    ) -> Result<Message, Error> {
+        self.do_request(subject, Some(headers), payload, self.request_timeout)
+            .await
+            .map_err(|err| Box::new(err) as Error)
+    }
+
+    /// Like [`Client::request`], but overrides the client's default request
+    /// timeout (if any) for this single call. Passing `None` waits forever,
+    /// the same as [`Client::request`] on a client with no default timeout
+    /// configured.
+    ///
+    /// If the timeout elapses before a reply (or a `NoResponders` status)
+    /// arrives, the returned error is [`RequestError::Timeout`].
+    pub async fn request_timeout(
+        &self,
+        subject: String,
+        payload: Bytes,
+        timeout: Option<Duration>,
+    ) -> Result<Message, RequestError> {
+        self.do_request(subject, None, payload, timeout).await
+    }
+
+    async fn do_request(
+        &self,
+        subject: String,
+        headers: Option<HeaderMap>,
+        payload: Bytes,
+        timeout: Option<Duration>,
+    ) -> Result<Message, RequestError> {
+        let reply = if self.no_multiplexing {
+            self.request_isolated(subject, headers, payload)
+        } else {
+            self.request_multiplexed(subject, headers, payload)
+        };
+        match timeout {
+            Some(duration) => tokio::time::timeout(duration, reply)
+                .await
+                .map_err(|_| RequestError::Timeout)?,
+            None => reply.await,
+        }
+    }
+
+    /// Old-style request behavior: a fresh subscription is created and torn
+    /// down for every call. Used when [`ConnectOptions::no_multiplexing`] is
+    /// set.
+    async fn request_isolated(
+        &self,
+        subject: String,
+        headers: Option<HeaderMap>,
+        payload: Bytes,
+    ) -> Result<Message, RequestError> {
         let inbox = self.new_inbox();
-        let mut sub = self.subscribe(inbox.clone()).await?;
-        self.publish_with_reply(subject, inbox, payload).await?;
-        self.flush().await?;
+        let mut sub = self
+            .subscribe(inbox.clone())
+            .await
+            .map_err(|_| RequestError::BrokenPipe)?;
+        let publish_result = match headers {
+            Some(headers) => {
+                self.publish_with_reply_and_headers(subject, inbox, headers, payload)
+                    .await
+            }
+            None => self.publish_with_reply(subject, inbox, payload).await,
+        };
+        publish_result.map_err(|_| RequestError::BrokenPipe)?;
+        self.flush().await.map_err(|_| RequestError::BrokenPipe)?;
         match sub.next().await {
             Some(message) => {
                 if message.status == Some(StatusCode::NO_RESPONDERS) {
-                    return Err(Box::new(std::io::Error::new(
-                        ErrorKind::NotFound,
-                        "nats: no responders",
-                    )));
+                    return Err(RequestError::NoResponders);
                 }
                 Ok(message)
             }
-            None => Err(Box::new(io::Error::new(
-                ErrorKind::BrokenPipe,
-                "did not receive any message",
-            ))),
+            None => Err(RequestError::BrokenPipe),
         }
     }
 
-    pub async fn request_with_headers(
+    /// Default request behavior: replies are routed through a single
+    /// long-lived wildcard subscription (the "mux" inbox) shared by every
+    /// outstanding request on this client, rather than a subscription per
+    /// call.
+    async fn request_multiplexed(
         &self,
         subject: String,
-        headers: HeaderMap,
+        headers: Option<HeaderMap>,
         payload: Bytes,
-    ) -> Result<Message, Error> {
-        let inbox = self.new_inbox();
-        let mut sub = self.subscribe(inbox.clone()).await?;
-        self.publish_with_reply_and_headers(subject, inbox, headers, payload)
-            .await?;
-        self.flush().await?;
-        match sub.next().await {
-            Some(message) => {
+    ) -> Result<Message, RequestError> {
+        let mux = self
+            .mux_subscribe()
+            .await
+            .map_err(|_| RequestError::BrokenPipe)?;
+        let token = nuid::next();
+        let reply = format!("{}{}", mux.prefix, token);
+
+        let (sender, receiver) = oneshot::channel();
+        mux.senders.lock().await.insert(token.clone(), sender);
+        // Guards against `do_request`'s `tokio::time::timeout` dropping this
+        // future (and the `receiver.await` below with it) before a reply
+        // arrives: without it the map entry would never be removed, since
+        // the router only removes tokens for replies it actually delivers.
+        let mut guard = MuxGuard::new(mux.clone(), token.clone());
+
+        let publish_result = match headers {
+            Some(headers) => {
+                self.publish_with_reply_and_headers(subject, reply, headers, payload)
+                    .await
+            }
+            None => self.publish_with_reply(subject, reply, payload).await,
+        };
+        if publish_result.is_err() {
+            mux.senders.lock().await.remove(&token);
+            guard.disarm();
+            return Err(RequestError::BrokenPipe);
+        }
+        self.flush().await.map_err(|_| RequestError::BrokenPipe)?;
+
+        let result = receiver.await;
+        // Whether the router delivered a reply or its sender was dropped, the
+        // map entry is already gone by the time `receiver.await` resolves.
+        guard.disarm();
+        match result {
+            Ok(message) => {
                 if message.status == Some(StatusCode::NO_RESPONDERS) {
-                    return Err(Box::new(std::io::Error::new(
-                        ErrorKind::NotFound,
-                        "nats: no responders",
-                    )));
+                    return Err(RequestError::NoResponders);
                 }
                 Ok(message)
             }
-            None => Err(Box::new(io::Error::new(
-                ErrorKind::BrokenPipe,
-                "did not receive any message",
-            ))),
+            Err(_) => Err(RequestError::BrokenPipe),
+        }
+    }
+
+    /// Returns the shared [`Multiplexer`], lazily subscribing to the wildcard
+    /// mux inbox (`_INBOX.<nuid>.*`) on first use and spawning the background
+    /// task that routes replies to the waiting request by their unique token.
+    async fn mux_subscribe(&self) -> Result<Arc<Multiplexer>, io::Error> {
+        let mut guard = self.mux.lock().await;
+        if let Some(mux) = guard.as_ref() {
+            return Ok(mux.clone());
         }
+
+        let prefix = format!("_INBOX.{}.", nuid::next());
+        let mut sub = self._subscribe(format!("{}*", prefix), None).await?;
+        let mux = Arc::new(Multiplexer {
+            prefix,
+            senders: Mutex::new(HashMap::new()),
+        });
+
+        let router = mux.clone();
+        tokio::spawn(async move {
+            while let Some(message) = sub.next().await {
+                let token = message.subject.rsplit('.').next().unwrap_or_default();
+                let sender = router.senders.lock().await.remove(token);
+                if let Some(sender) = sender {
+                    let _ = sender.send(message);
+                }
+            }
+            // The subscription has ended: drop every pending sender so the
+            // waiting requests resolve with `RequestError::BrokenPipe`.
+            router.senders.lock().await.clear();
+        });
+
+        *guard = Some(mux.clone());
+        Ok(mux)
     }
 
     /// Create a new globally unique inbox which can be used for replies.
@@ -211,4 +425,110 @@ impl Client {
         rx.await??;
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Drains the connection: every active subscription stops accepting new
+    /// messages (while already-buffered ones remain consumable, same as
+    /// [`Subscriber::drain`]), pending publishes are flushed to the server,
+    /// and the connection then transitions to a closed state that rejects any
+    /// further `publish`/`subscribe` calls.
+    ///
+    /// Use this for clean shutdown so in-flight work started by existing
+    /// subscriptions isn't dropped, instead of simply letting the `Client` go
+    /// out of scope.
+    pub async fn drain(&self) -> Result<(), Error> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.sender.send(Command::Drain { result: tx }).await?;
+        rx.await??;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_message(subject: &str) -> Message {
+        Message {
+            subject: subject.to_string(),
+            reply: None,
+            payload: Bytes::new(),
+            headers: None,
+            status: None,
+            description: None,
+        }
+    }
+
+    #[test]
+    fn request_error_display_is_distinct_per_variant() {
+        assert_eq!(
+            RequestError::NoResponders.to_string(),
+            "nats: no responders"
+        );
+        assert_eq!(RequestError::Timeout.to_string(), "nats: request timed out");
+        assert_eq!(
+            RequestError::BrokenPipe.to_string(),
+            "nats: did not receive any message"
+        );
+    }
+
+    #[tokio::test]
+    async fn mux_guard_removes_entry_when_dropped_before_reply() {
+        let mux = Arc::new(Multiplexer {
+            prefix: "_INBOX.test.".to_string(),
+            senders: Mutex::new(HashMap::new()),
+        });
+        let (sender, _receiver) = oneshot::channel();
+        let token = "abc123".to_string();
+        mux.senders.lock().await.insert(token.clone(), sender);
+
+        // Simulates `do_request`'s `tokio::time::timeout` dropping the
+        // in-flight request future (and this guard with it) before a reply
+        // arrives.
+        let guard = MuxGuard::new(mux.clone(), token.clone());
+        drop(guard);
+
+        // The cleanup runs in a spawned task; give it a chance to run.
+        tokio::task::yield_now().await;
+        tokio::task::yield_now().await;
+
+        assert!(!mux.senders.lock().await.contains_key(&token));
+    }
+
+    #[tokio::test]
+    async fn disarmed_mux_guard_leaves_a_reused_token_alone() {
+        let mux = Arc::new(Multiplexer {
+            prefix: "_INBOX.test.".to_string(),
+            senders: Mutex::new(HashMap::new()),
+        });
+        let token = "abc123".to_string();
+        let (first_sender, _first_receiver) = oneshot::channel();
+        mux.senders.lock().await.insert(token.clone(), first_sender);
+
+        // Simulates the router delivering a reply and `request_multiplexed`
+        // disarming the guard because cleanup already happened.
+        let mut guard = MuxGuard::new(mux.clone(), token.clone());
+        mux.senders.lock().await.remove(&token);
+        guard.disarm();
+        drop(guard);
+
+        // A later, unrelated request is free to reuse the same token...
+        let (second_sender, second_receiver) = oneshot::channel();
+        mux.senders
+            .lock()
+            .await
+            .insert(token.clone(), second_sender);
+        let message = test_message("_INBOX.test.abc123");
+        mux.senders
+            .lock()
+            .await
+            .remove(&token)
+            .unwrap()
+            .send(message.clone())
+            .unwrap();
+
+        // ...and the disarmed guard's drop must not have raced in and
+        // deregistered it first.
+        tokio::task::yield_now().await;
+        assert_eq!(second_receiver.await.unwrap().subject, message.subject);
+    }
+}
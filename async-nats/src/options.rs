@@ -0,0 +1,104 @@
+// Copyright 2020-2022 The NATS Authors
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::Serialize;
+
+/// The subset of the CONNECT protocol message (the first line a client sends
+/// a NATS server after the TCP handshake) that is controlled by
+/// [`ConnectOptions`]. Built by [`ConnectOptions::connect_info`] and handed to
+/// the connector, which serializes it as JSON onto the `CONNECT {...}` line.
+#[derive(Debug, Serialize)]
+pub(crate) struct ConnectInfo {
+    pub(crate) echo: bool,
+}
+
+/// Options used to establish a connection with a NATS server, typically built
+/// with the fluent setters below and handed to [`crate::connect_with_options`].
+#[derive(Clone, Debug)]
+pub struct ConnectOptions {
+    pub(crate) no_multiplexing: bool,
+    pub(crate) request_timeout: Option<std::time::Duration>,
+    pub(crate) echo: bool,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        ConnectOptions {
+            no_multiplexing: false,
+            request_timeout: None,
+            echo: true,
+        }
+    }
+}
+
+impl ConnectOptions {
+    pub fn new() -> ConnectOptions {
+        ConnectOptions::default()
+    }
+
+    /// Disables the shared multiplexed inbox used by [`crate::Client::request`]
+    /// and [`crate::Client::request_with_headers`], falling back to the
+    /// previous behavior of creating and tearing down a brand-new subscription
+    /// for every request.
+    ///
+    /// This trades away the reduced subscription/allocation overhead of the
+    /// default multiplexed inbox for fully isolated per-request subscriptions,
+    /// which some deployments prefer for server-side accounting or testing.
+    pub fn no_multiplexing(mut self) -> ConnectOptions {
+        self.no_multiplexing = true;
+        self
+    }
+
+    /// Sets the default timeout applied to [`crate::Client::request`] and
+    /// [`crate::Client::request_with_headers`] calls that don't specify their
+    /// own timeout. `None` (the default) waits forever, matching the
+    /// historical behavior.
+    pub fn request_timeout(mut self, timeout: Option<std::time::Duration>) -> ConnectOptions {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Disables echo, so that messages this client publishes are not
+    /// delivered back to its own subscriptions even if they match. Sent as
+    /// the `echo: false` field of the CONNECT protocol message.
+    ///
+    /// Without this, a client that both publishes and subscribes on
+    /// overlapping subjects (for example while doing request/reply) would
+    /// normally see its own traffic come back and have to filter it out
+    /// manually.
+    pub fn no_echo(mut self) -> ConnectOptions {
+        self.echo = false;
+        self
+    }
+
+    /// Builds the [`ConnectInfo`] the connector serializes onto the `CONNECT`
+    /// protocol line when it establishes the connection.
+    pub(crate) fn connect_info(&self) -> ConnectInfo {
+        ConnectInfo { echo: self.echo }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn echo_defaults_to_enabled() {
+        assert!(ConnectOptions::default().connect_info().echo);
+    }
+
+    #[test]
+    fn no_echo_is_reflected_in_connect_info() {
+        assert!(!ConnectOptions::new().no_echo().connect_info().echo);
+    }
+}
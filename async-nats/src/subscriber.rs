@@ -0,0 +1,274 @@
+// Copyright 2020-2022 The NATS Authors
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{Command, Error, Message};
+use futures::stream::{Stream, StreamExt};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{self, ErrorKind};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// A handle to a subscription created with [`crate::Client::subscribe`] or
+/// [`crate::Client::queue_subscribe`]. Implements [`Stream`] to yield the
+/// messages delivered to it.
+#[derive(Debug)]
+pub struct Subscriber {
+    sid: u64,
+    sender: mpsc::Sender<Command>,
+    receiver: mpsc::Receiver<Message>,
+}
+
+impl Subscriber {
+    pub(crate) fn new(
+        sid: u64,
+        sender: mpsc::Sender<Command>,
+        receiver: mpsc::Receiver<Message>,
+    ) -> Subscriber {
+        Subscriber {
+            sid,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Unsubscribes immediately, without waiting for any already-buffered
+    /// messages to be consumed: any messages that arrived before the server
+    /// processed the unsubscribe are discarded from the local buffer right
+    /// away. Prefer [`Subscriber::drain`] for a graceful shutdown.
+    pub async fn unsubscribe(&mut self) -> Result<(), io::Error> {
+        self.sender
+            .send(Command::Unsubscribe { sid: self.sid })
+            .await
+            .map_err(|err| io::Error::new(ErrorKind::Other, err.to_string()))?;
+        self.receiver.close();
+        while self.receiver.try_recv().is_ok() {}
+        Ok(())
+    }
+
+    /// Stops the server from routing any new messages to this subscription,
+    /// while leaving already-buffered messages in place so they can still be
+    /// consumed by continuing to poll this `Subscriber` as a [`Stream`] until
+    /// it returns `None`.
+    ///
+    /// Unlike [`Subscriber::unsubscribe`] (or simply dropping the
+    /// `Subscriber`), no in-flight messages are discarded: this only sends
+    /// the UNSUB and leaves the local buffer alone.
+    pub async fn drain(&mut self) -> Result<(), io::Error> {
+        self.sender
+            .send(Command::Unsubscribe { sid: self.sid })
+            .await
+            .map_err(|err| io::Error::new(ErrorKind::Other, err.to_string()))
+    }
+
+    /// Spawns a task that consumes this subscription and invokes the async
+    /// `handler` for every message, saving the caller from hand-writing the
+    /// `while let Some(message) = subscriber.next().await` loop. Returns a
+    /// [`Handle`] which can be used to stop the task or await its outcome.
+    ///
+    /// Use [`Subscriber::with_blocking_handler`] for a handler that doesn't
+    /// need to `.await` anything.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() -> std::io::Result<()> {
+    /// # let nc = async_nats::connect("demo.nats.io").await?;
+    /// let handle = nc.subscribe("bar".into()).await?.with_handler(move |message| async move {
+    ///     println!("received {:?}", message);
+    ///     Ok(())
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_handler<F, Fut>(mut self, handler: F) -> Handle
+    where
+        F: Fn(Message) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        let join_handle = tokio::spawn(async move {
+            while let Some(message) = self.next().await {
+                handler(message).await?;
+            }
+            Ok(())
+        });
+        Handle { join_handle }
+    }
+
+    /// Like [`Subscriber::with_handler`], but for a plain, non-async handler
+    /// closure.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() -> std::io::Result<()> {
+    /// # let nc = async_nats::connect("demo.nats.io").await?;
+    /// let handle = nc.subscribe("bar".into()).await?.with_blocking_handler(move |message| {
+    ///     println!("received {:?}", message);
+    ///     Ok(())
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_blocking_handler<F>(mut self, handler: F) -> Handle
+    where
+        F: Fn(Message) -> Result<(), Error> + Send + Sync + 'static,
+    {
+        let join_handle = tokio::spawn(async move {
+            while let Some(message) = self.next().await {
+                handler(message)?;
+            }
+            Ok(())
+        });
+        Handle { join_handle }
+    }
+}
+
+/// A handle to a subscription handler task spawned by
+/// [`Subscriber::with_handler`]. Dropping it does not stop the task; call
+/// [`Handle::stop`] to cancel it, or await it to observe the handler's
+/// outcome.
+#[derive(Debug)]
+pub struct Handle {
+    join_handle: JoinHandle<Result<(), Error>>,
+}
+
+impl Handle {
+    /// Cancels the handler task, which also drops the underlying
+    /// `Subscriber` and unsubscribes.
+    pub fn stop(&self) {
+        self.join_handle.abort();
+    }
+}
+
+impl Future for Handle {
+    type Output = Result<(), Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Pin::new(&mut self.join_handle).poll(cx) {
+            Poll::Ready(Ok(result)) => Poll::Ready(result),
+            Poll::Ready(Err(join_err)) => {
+                if join_err.is_panic() {
+                    std::panic::resume_unwind(join_err.into_panic());
+                }
+                Poll::Ready(Ok(()))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Stream for Subscriber {
+    type Item = Message;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        let sid = self.sid;
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            sender.send(Command::Unsubscribe { sid }).await.ok();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn test_subscriber() -> (Subscriber, mpsc::Sender<Message>, mpsc::Receiver<Command>) {
+        let (command_tx, command_rx) = mpsc::channel(16);
+        let (message_tx, message_rx) = mpsc::channel(16);
+        (
+            Subscriber::new(0, command_tx, message_rx),
+            message_tx,
+            command_rx,
+        )
+    }
+
+    fn test_message(subject: &str) -> Message {
+        Message {
+            subject: subject.to_string(),
+            reply: None,
+            payload: Bytes::new(),
+            headers: None,
+            status: None,
+            description: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn unsubscribe_discards_already_buffered_messages() {
+        let (mut sub, message_tx, _command_rx) = test_subscriber();
+        message_tx.send(test_message("buffered")).await.unwrap();
+
+        sub.unsubscribe().await.unwrap();
+
+        assert!(sub.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn drain_leaves_already_buffered_messages_consumable() {
+        let (mut sub, message_tx, _command_rx) = test_subscriber();
+        message_tx.send(test_message("buffered")).await.unwrap();
+
+        sub.drain().await.unwrap();
+
+        assert_eq!(sub.next().await.unwrap().subject, "buffered");
+    }
+
+    #[tokio::test]
+    async fn with_handler_invokes_the_async_handler_per_message() {
+        let (sub, message_tx, _command_rx) = test_subscriber();
+        let (seen_tx, mut seen_rx) = mpsc::channel(16);
+
+        let handle = sub.with_handler(move |message| {
+            let seen_tx = seen_tx.clone();
+            async move {
+                seen_tx.send(message.subject).await.ok();
+                Ok(())
+            }
+        });
+
+        message_tx.send(test_message("one")).await.unwrap();
+        message_tx.send(test_message("two")).await.unwrap();
+        drop(message_tx);
+
+        assert_eq!(seen_rx.recv().await.unwrap(), "one");
+        assert_eq!(seen_rx.recv().await.unwrap(), "two");
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn with_blocking_handler_invokes_the_handler_per_message() {
+        let (sub, message_tx, _command_rx) = test_subscriber();
+        let (seen_tx, mut seen_rx) = mpsc::channel(16);
+
+        let handle = sub.with_blocking_handler(move |message| {
+            seen_tx.try_send(message.subject).ok();
+            Ok(())
+        });
+
+        message_tx.send(test_message("one")).await.unwrap();
+        drop(message_tx);
+
+        assert_eq!(seen_rx.recv().await.unwrap(), "one");
+        handle.await.unwrap();
+    }
+}